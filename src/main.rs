@@ -16,11 +16,23 @@ this program. If not, see <http://www.gnu.org/licenses/>.
 
 use rouille::{Request, Response, ResponseBody};
 use std::io::Read;
+use std::io::Write;
 use std::os::unix::fs::PermissionsExt;
 use nix::unistd::{Uid, Gid, chown};
 use std::fs::File;
 use clap::Parser;
 use serde_derive::{Serialize, Deserialize};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as B64;
+use subtle::ConstantTimeEq;
+use std::io::BufRead;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "mint-token")]
+use clap::Subcommand;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct Command {
@@ -29,17 +41,63 @@ struct Command {
     read_file: Option<String>,
     read_bin_file: Option<String>,
     write_file: Option<String>,
+    write_bin_file: Option<String>,
+    list_dir: Option<String>,
+    stream: Option<bool>,
+    async_job: Option<bool>,
     user: Option<String>,
     group: Option<String>,
     mode: Option<u32>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct JobReport {
+    state: String,
+    started_at: String,
+    finished_at: Option<String>,
+    retcode: Option<i32>,
+    stdout: String,
+    stderr: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JobStarted {
+    job_id: String,
+}
+
+struct JobHandle {
+    // The endpoint the job was started from, so /jobs/{id} can be scope-checked like
+    // any other endpoint instead of being reachable by any authenticated token.
+    endpoint: String,
+    info: Arc<Mutex<JobReport>>,
+    child: Option<Arc<Mutex<std::process::Child>>>,
+}
+
+type JobRegistry = Arc<Mutex<HashMap<String, JobHandle>>>;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct DirEntryInfo {
+    name: String,
+    size: u64,
+    file_type: String,
+    modified: String,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct Config {
     listen: String,
     listen_port: u16,
     apikey: String,
     commands: Vec<Command>,
+    tls_cert: Option<String>,
+    tls_key: Option<String>,
+    secret: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct TokenPayload {
+    exp: u64,
+    scopes: Vec<String>,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -94,50 +152,106 @@ fn read_file(filename: &str) -> Response {
     }
 }
 
-fn write_file(cmd: &Command, request: &Request) -> Response{
-    let cmdfn = cmd.write_file.clone().expect("File name to write is missing in command configration");
-    let filename = expand_vars(&cmdfn, request);
-    let mut rdata = match request.data() {
-        Some(data) => data,
-        None => {
-            println!("write_file {} failed due to no data read from HTTP request.", filename);
+fn classify_file_type(name: &str) -> String {
+    let ext = std::path::Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    match ext.as_str() {
+        "zip" | "tar" | "gz" | "bz2" | "xz" | "7z" | "rar" => "archive",
+        "jpg" | "jpeg" | "png" | "gif" | "bmp" | "svg" | "webp" => "image",
+        "rs" | "c" | "cpp" | "h" | "py" | "js" | "ts" | "go" | "java" | "sh" => "code",
+        "txt" | "md" | "pdf" | "doc" | "docx" | "odt" => "document",
+        "" => "unknown",
+        _ => "other",
+    }.to_string()
+}
+
+fn list_dir(cmd: &Command, request: &Request) -> Response {
+    let root = cmd.list_dir.clone().expect("Root directory to list is missing in command configuration");
+    let subdir = request.get_param("path").unwrap_or(String::new());
+    let target = std::path::Path::new(&root).join(&subdir);
+
+    let canon_root = match std::fs::canonicalize(&root) {
+        Ok(p) => p,
+        Err(err) => {
+            println!("list_dir {} failed to canonicalize root: {}", root, err);
             return empty_with_status(500);
         }
     };
-    let mut sdata = String::new();
-    match rdata.read_to_string(&mut sdata) {
-        Ok(_) => {},
+    let canon_target = match std::fs::canonicalize(&target) {
+        Ok(p) => p,
         Err(err) => {
-            println!("write_file {} failed to extract data from HTTP request: {}", filename, err);
-            return empty_with_status(500);
+            println!("list_dir {} failed to canonicalize {}: {}", root, subdir, err);
+            return Response::empty_404();
         }
+    };
+    if !canon_target.starts_with(&canon_root) {
+        println!("list_dir {} failed: {} escapes root", root, subdir);
+        return empty_with_status(403);
     }
 
-    match std::fs::write(&filename, sdata) {
-        Ok(_) => {
-            println!("write_file {} success", filename);         
-        },
+    let read_dir = match std::fs::read_dir(&canon_target) {
+        Ok(rd) => rd,
         Err(err) => {
-            println!("write_file {} failed: {}", filename, err);
+            println!("list_dir {} failed: {}", canon_target.display(), err);
             return empty_with_status(500);
         }
+    };
+
+    let mut entries = Vec::new();
+    for entry in read_dir {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(err) => {
+                println!("list_dir {} failed to read an entry: {}", canon_target.display(), err);
+                continue;
+            }
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+        let metadata = match entry.metadata() {
+            Ok(md) => md,
+            Err(err) => {
+                println!("list_dir {} failed to stat {}: {}", canon_target.display(), name, err);
+                continue;
+            }
+        };
+        let modified = metadata.modified()
+            .map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339())
+            .unwrap_or_else(|_| String::new());
+
+        entries.push(DirEntryInfo {
+            file_type: if metadata.is_dir() { String::from("directory") } else { classify_file_type(&name) },
+            name,
+            size: metadata.len(),
+            modified,
+        });
     }
 
+    println!("list_dir {} success", canon_target.display());
+    Response::json(&entries)
+}
+
+// Applies the mode/user/group ownership configured on `cmd` to `filename`.
+// Returns an error Response on the first failure, or None on success.
+fn apply_ownership(filename: &str, cmd: &Command, logtag: &str) -> Option<Response> {
     match cmd.mode {
         Some(some_mode) => {
-            let mut perms = match std::fs::metadata(&filename){
+            let mut perms = match std::fs::metadata(filename){
                 Ok(md) => md.permissions(),
                 Err(err) => {
-                    println!("write_file {} failed to get metadata: {}", filename, err);
-                    return empty_with_status(500);
+                    println!("{} {} failed to get metadata: {}", logtag, filename, err);
+                    return Some(empty_with_status(500));
                 }
             };
             perms.set_mode(some_mode);
-            match std::fs::set_permissions(&filename, perms) {
+            match std::fs::set_permissions(filename, perms) {
                 Ok(_) => (),
                 Err(err) => {
-                    println!("write_file {} failed to set metadata: {}", filename, err);
-                    return empty_with_status(500);
+                    println!("{} {} failed to set metadata: {}", logtag, filename, err);
+                    return Some(empty_with_status(500));
                 }
             }
         },
@@ -149,15 +263,15 @@ fn write_file(cmd: &Command, request: &Request) -> Response{
             let uid = match users::get_user_by_name(some_user) {
                 Some(u) => u.uid(),
                 None => {
-                    println!("write_file {} failed: user {} not found", filename, some_user);
-                    return empty_with_status(500);
+                    println!("{} {} failed: user {} not found", logtag, filename, some_user);
+                    return Some(empty_with_status(500));
                 }
             };
-            match chown(filename.as_str(), Some(Uid::from_raw(uid)), None) {
+            match chown(filename, Some(Uid::from_raw(uid)), None) {
                 Ok(_) => (),
                 Err(err) => {
-                    println!("write_file {} failed to set uid: {}", filename, err);
-                    return empty_with_status(500);
+                    println!("{} {} failed to set uid: {}", logtag, filename, err);
+                    return Some(empty_with_status(500));
                 }
             };
         },
@@ -169,21 +283,103 @@ fn write_file(cmd: &Command, request: &Request) -> Response{
             let gid = match users::get_group_by_name(some_group) {
                 Some(g) => g.gid(),
                 None => {
-                    println!("write_file {} failed: group {} not found", filename, some_group);
-                    return empty_with_status(500);
+                    println!("{} {} failed: group {} not found", logtag, filename, some_group);
+                    return Some(empty_with_status(500));
                 }
             };
-            match chown(filename.as_str(), None, Some(Gid::from_raw(gid))) {
+            match chown(filename, None, Some(Gid::from_raw(gid))) {
                 Ok(_) => (),
                 Err(err) => {
-                    println!("write_file {} failed to set gid: {}", filename, err);
-                    return empty_with_status(500);
+                    println!("{} {} failed to set gid: {}", logtag, filename, err);
+                    return Some(empty_with_status(500));
                 }
             };
         },
         None => ()
     }
 
+    None
+}
+
+fn write_file(cmd: &Command, request: &Request) -> Response{
+    let cmdfn = cmd.write_file.clone().expect("File name to write is missing in command configration");
+    let filename = expand_vars(&cmdfn, request);
+    let mut rdata = match request.data() {
+        Some(data) => data,
+        None => {
+            println!("write_file {} failed due to no data read from HTTP request.", filename);
+            return empty_with_status(500);
+        }
+    };
+    let mut sdata = String::new();
+    match rdata.read_to_string(&mut sdata) {
+        Ok(_) => {},
+        Err(err) => {
+            println!("write_file {} failed to extract data from HTTP request: {}", filename, err);
+            return empty_with_status(500);
+        }
+    }
+
+    match std::fs::write(&filename, sdata) {
+        Ok(_) => {
+            println!("write_file {} success", filename);
+        },
+        Err(err) => {
+            println!("write_file {} failed: {}", filename, err);
+            return empty_with_status(500);
+        }
+    }
+
+    if let Some(err_resp) = apply_ownership(&filename, cmd, "write_file") {
+        return err_resp;
+    }
+
+    empty_with_status(201)
+}
+
+fn write_bin_file(cmd: &Command, request: &Request) -> Response{
+    let cmdfn = cmd.write_bin_file.clone().expect("File name to write is missing in command configration");
+    let filename = expand_vars(&cmdfn, request);
+    let mut rdata = match request.data() {
+        Some(data) => data,
+        None => {
+            println!("write_bin_file {} failed due to no data read from HTTP request.", filename);
+            return empty_with_status(500);
+        }
+    };
+
+    let file = match File::create(&filename) {
+        Ok(f) => f,
+        Err(err) => {
+            println!("write_bin_file {} failed to create file: {}", filename, err);
+            return empty_with_status(500);
+        }
+    };
+    let mut writer = std::io::BufWriter::new(file);
+    match std::io::copy(&mut rdata, &mut writer) {
+        Ok(written) => {
+            println!("write_bin_file {} streamed {} bytes", filename, written);
+        },
+        Err(err) => {
+            println!("write_bin_file {} failed to stream data: {}", filename, err);
+            return empty_with_status(500);
+        }
+    }
+
+    match writer.flush() {
+        Ok(_) => {
+            println!("write_bin_file {} success", filename);
+        },
+        Err(err) => {
+            println!("write_bin_file {} failed to flush data to disk: {}", filename, err);
+            return empty_with_status(500);
+        }
+    }
+
+    if let Some(err_resp) = apply_ownership(&filename, cmd, "write_bin_file") {
+        return err_resp;
+    }
+
     empty_with_status(201)
 }
 
@@ -207,6 +403,207 @@ fn shell(cmd: &str, request: &Request) -> Response {
     }
 }
 
+// Runs `cmd` in the background and forwards its stdout/stderr over a WebSocket
+// line by line as it is produced, followed by a final frame with the exit code.
+fn shell_stream(cmd: &str, request: &Request) -> Response {
+    let (response, websocket) = match rouille::websocket::start(request, None::<String>) {
+        Ok(r) => r,
+        Err(err) => {
+            println!("command {} failed to upgrade to websocket: {:?}", cmd, err);
+            return empty_with_status(400);
+        }
+    };
+
+    let cmd = cmd.to_string();
+    std::thread::spawn(move || {
+        let ws = match websocket.recv() {
+            Ok(ws) => ws,
+            Err(err) => {
+                println!("command {} failed to complete websocket handshake: {:?}", cmd, err);
+                return;
+            }
+        };
+
+        let mut child = match std::process::Command::new("sh").arg("-c").arg(&cmd)
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn() {
+                Ok(c) => c,
+                Err(err) => {
+                    println!("command {} failed to spawn: {}", cmd, err);
+                    return;
+                }
+            };
+
+        let stdout = child.stdout.take().expect("child stdout was piped");
+        let stderr = child.stderr.take().expect("child stderr was piped");
+        let ws = std::sync::Arc::new(std::sync::Mutex::new(ws));
+
+        let out_ws = ws.clone();
+        let out_tag = cmd.clone();
+        let out_handle = std::thread::spawn(move || {
+            for_each_line(stdout, &out_tag, |line| { out_ws.lock().unwrap().send_text(&line); });
+        });
+
+        let err_ws = ws.clone();
+        let err_tag = cmd.clone();
+        let err_handle = std::thread::spawn(move || {
+            for_each_line(stderr, &err_tag, |line| { err_ws.lock().unwrap().send_text(&line); });
+        });
+
+        out_handle.join().ok();
+        err_handle.join().ok();
+
+        let retcode = match child.wait() {
+            Ok(status) => status.code().unwrap_or(0),
+            Err(err) => {
+                println!("command {} failed to wait on child: {}", cmd, err);
+                -1
+            }
+        };
+
+        println!("command {} stream finished with exit code {}", cmd, retcode);
+        ws.lock().unwrap().send_text(&format!("{{\"exit_code\":{}}}", retcode));
+    });
+
+    response
+}
+
+fn now_rfc3339() -> String {
+    chrono::Utc::now().to_rfc3339()
+}
+
+// Reads `reader` line by line, calling `f` on each successfully decoded line and logging
+// (instead of silently discarding) any line that isn't valid UTF-8.
+fn for_each_line<R: std::io::Read>(reader: R, tag: &str, mut f: impl FnMut(String)) {
+    for line in std::io::BufReader::new(reader).lines() {
+        match line {
+            Ok(l) => f(l),
+            Err(err) => println!("{} dropped a non-UTF8 output line: {}", tag, err),
+        }
+    }
+}
+
+// Spawns `cmd` in the background, registers it under a fresh job ID and returns that ID
+// immediately. The job's stdout/stderr/retcode keep accumulating in `jobs` as it runs.
+fn start_async_job(endpoint: String, cmd: String, jobs: JobRegistry) -> String {
+    let id = uuid::Uuid::new_v4().to_string();
+    let info = Arc::new(Mutex::new(JobReport {
+        state: String::from("running"),
+        started_at: now_rfc3339(),
+        finished_at: None,
+        retcode: None,
+        stdout: String::new(),
+        stderr: String::new(),
+    }));
+
+    let mut child = match std::process::Command::new("sh").arg("-c").arg(&cmd)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn() {
+            Ok(c) => c,
+            Err(err) => {
+                println!("async_job {} failed to spawn {}: {}", id, cmd, err);
+                let mut i = info.lock().unwrap();
+                i.state = String::from("finished");
+                i.stderr = format!("failed to spawn: {}", err);
+                i.retcode = Some(-1);
+                i.finished_at = Some(now_rfc3339());
+                drop(i);
+                jobs.lock().unwrap().insert(id.clone(), JobHandle { endpoint, info, child: None });
+                return id;
+            }
+        };
+
+    let stdout = child.stdout.take().expect("child stdout was piped");
+    let stderr = child.stderr.take().expect("child stderr was piped");
+    let child = Arc::new(Mutex::new(child));
+
+    jobs.lock().unwrap().insert(id.clone(), JobHandle { endpoint, info: info.clone(), child: Some(child.clone()) });
+
+    let out_info = info.clone();
+    let out_id = id.clone();
+    let out_handle = std::thread::spawn(move || {
+        for_each_line(stdout, &format!("async_job {}", out_id), |line| {
+            let mut i = out_info.lock().unwrap();
+            i.stdout.push_str(&line);
+            i.stdout.push('\n');
+        });
+    });
+
+    let err_info = info.clone();
+    let err_id = id.clone();
+    let err_handle = std::thread::spawn(move || {
+        for_each_line(stderr, &format!("async_job {}", err_id), |line| {
+            let mut i = err_info.lock().unwrap();
+            i.stderr.push_str(&line);
+            i.stderr.push('\n');
+        });
+    });
+
+    let wait_id = id.clone();
+    std::thread::spawn(move || {
+        out_handle.join().ok();
+        err_handle.join().ok();
+
+        let retcode = match child.lock().unwrap().wait() {
+            Ok(status) => status.code().unwrap_or(0),
+            Err(err) => {
+                println!("async_job {} failed to wait on child: {}", wait_id, err);
+                -1
+            }
+        };
+
+        let mut i = info.lock().unwrap();
+        i.state = String::from("finished");
+        i.retcode = Some(retcode);
+        i.finished_at = Some(now_rfc3339());
+        println!("async_job {} finished with exit code {}", wait_id, retcode);
+    });
+
+    id
+}
+
+fn job_status(jobs: &JobRegistry, id: &str, scopes: &[String]) -> Response {
+    let registry = jobs.lock().unwrap();
+    let handle = match registry.get(id) {
+        Some(h) => h,
+        None => return Response::empty_404(),
+    };
+    if !endpoint_allowed(scopes, &handle.endpoint) {
+        println!("Forbidden: GET /jobs/{} (token scope does not cover the job's originating endpoint {})", id, handle.endpoint);
+        return empty_with_status(403);
+    }
+    Response::json(&*handle.info.lock().unwrap())
+}
+
+fn job_kill(jobs: &JobRegistry, id: &str, scopes: &[String]) -> Response {
+    let mut registry = jobs.lock().unwrap();
+    match registry.get(id) {
+        Some(h) => {
+            if !endpoint_allowed(scopes, &h.endpoint) {
+                println!("Forbidden: DELETE /jobs/{} (token scope does not cover the job's originating endpoint {})", id, h.endpoint);
+                return empty_with_status(403);
+            }
+        },
+        None => return Response::empty_404(),
+    };
+    let handle = registry.remove(id).expect("job was just confirmed present under the same lock");
+    drop(registry);
+
+    match &handle.child {
+        Some(child) => {
+            match child.lock().unwrap().kill() {
+                Ok(_) => println!("async_job {} killed", id),
+                Err(err) => println!("async_job {} kill failed (likely already finished): {}", id, err),
+            }
+        },
+        None => ()
+    }
+
+    empty_with_status(204)
+}
+
 fn expand_vars(input: &str, request: &Request) -> String {
     let maybestart = input.find('{');
     let maybeend = input.find('}');
@@ -227,7 +624,7 @@ fn expand_vars(input: &str, request: &Request) -> String {
     String::from(input)
 }
 
-fn execute(cmd: &Command, request: &Request) -> Response {
+fn execute(cmd: &Command, request: &Request, jobs: &JobRegistry) -> Response {
     let method = request.method();
     println!("Request {} {} {}", method, request.remote_addr(), request.raw_url());
     match method {
@@ -239,9 +636,23 @@ fn execute(cmd: &Command, request: &Request) -> Response {
                 None => ()
             }
 
+            match &cmd.write_bin_file {
+                Some(_) => {
+                    return write_bin_file(cmd, request);
+                },
+                None => ()
+            }
+
             match &cmd.shell {
                 Some(shellcmd) => {
                     let expcmd = expand_vars(shellcmd, request);
+                    if cmd.async_job.unwrap_or(false) {
+                        let job_id = start_async_job(cmd.endpoint.clone(), expcmd, jobs.clone());
+                        return Response::json(&JobStarted { job_id }).with_status_code(202);
+                    }
+                    if cmd.stream.unwrap_or(false) {
+                        return shell_stream(&expcmd, request);
+                    }
                     return shell(&expcmd, request);
                 },
                 None => ()
@@ -251,6 +662,13 @@ fn execute(cmd: &Command, request: &Request) -> Response {
             return empty_with_status(500);
         },
         "GET" => {
+            match &cmd.list_dir {
+                Some(_) => {
+                    return list_dir(cmd, request);
+                },
+                None => ()
+            }
+
             match &cmd.read_bin_file {
                 Some(filename) => {
                     let expfilename = expand_vars(filename, request);
@@ -270,6 +688,13 @@ fn execute(cmd: &Command, request: &Request) -> Response {
             match &cmd.shell {
                 Some(shellcmd) => {
                     let expcmd = expand_vars(shellcmd, request);
+                    if cmd.async_job.unwrap_or(false) {
+                        let job_id = start_async_job(cmd.endpoint.clone(), expcmd, jobs.clone());
+                        return Response::json(&JobStarted { job_id }).with_status_code(202);
+                    }
+                    if cmd.stream.unwrap_or(false) {
+                        return shell_stream(&expcmd, request);
+                    }
                     return shell(&expcmd, request);
                 },
                 None => ()
@@ -291,25 +716,123 @@ fn execute(cmd: &Command, request: &Request) -> Response {
     }
 }
 
-fn check_auth(request: &Request, conf: &Config) -> bool {
+// Parses the configured cert chain and private key and builds a real rustls::ServerConfig,
+// so an unreadable or unparsable PEM file, or an unsupported key type, panics at startup
+// rather than surfacing later as an obscure handshake failure. It does not check that the
+// private key belongs to the certificate.
+fn load_rustls_server_config(cert_path: &str, key_path: &str) -> rustls::ServerConfig {
+    let cert_chain = rustls_pemfile::certs(&mut std::io::BufReader::new(
+            File::open(cert_path).expect("Unable to open TLS certificate file.")))
+        .expect("Failed to parse TLS certificate file.")
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+
+    let mut key_reader = std::io::BufReader::new(
+        File::open(key_path).expect("Unable to open TLS private key file."));
+    let key = loop {
+        match rustls_pemfile::read_one(&mut key_reader).expect("Failed to parse TLS private key file.") {
+            Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => break rustls::PrivateKey(key),
+            Some(_) => continue,
+            None => panic!("TLS private key file contains no PKCS#1/PKCS#8/EC private key."),
+        }
+    };
+
+    rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)
+        .expect("rustls rejected the configured TLS certificate/key pair.")
+}
+
+fn current_unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("System time is before the Unix epoch")
+        .as_secs()
+}
+
+fn sign_payload(secret: &str, payload_b64: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(payload_b64.as_bytes());
+    B64.encode(mac.finalize().into_bytes())
+}
+
+fn mint_token(secret: &str, exp: u64, scopes: Vec<String>) -> String {
+    let payload = TokenPayload { exp, scopes };
+    let payload_b64 = B64.encode(serde_json::to_vec(&payload).expect("TokenPayload always serializes"));
+    let sig_b64 = sign_payload(secret, &payload_b64);
+    format!("{}.{}", payload_b64, sig_b64)
+}
+
+fn verify_token(token: &str, secret: &str) -> Option<TokenPayload> {
+    let (payload_b64, sig_b64) = token.split_once('.')?;
+    let expected_sig = sign_payload(secret, payload_b64);
+    if !bool::from(sig_b64.as_bytes().ct_eq(expected_sig.as_bytes())) {
+        return None;
+    }
+
+    let payload_json = B64.decode(payload_b64).ok()?;
+    let payload: TokenPayload = serde_json::from_slice(&payload_json).ok()?;
+    if payload.exp < current_unix_time() {
+        return None;
+    }
+    Some(payload)
+}
+
+// Returns the scopes granted to the caller, or None if the request is unauthorized.
+fn check_auth(request: &Request, conf: &Config) -> Option<Vec<String>> {
     for h in request.headers() {
         let (k,v) = h;
-        if k == "Authorization" && v == conf.apikey {
-            return true;
+        if k == "Authorization" {
+            return match &conf.secret {
+                Some(secret) => verify_token(v, secret).map(|p| p.scopes),
+                None => {
+                    if bool::from(v.as_bytes().ct_eq(conf.apikey.as_bytes())) {
+                        Some(vec![String::from("*")])
+                    } else {
+                        None
+                    }
+                }
+            };
         }
     }
-    false
+    None
 }
 
-fn handle_http_request(request: &Request, conf: &Config) -> Response {
-    if ! check_auth(request, conf) {
-        println!("Authorizatin Failed: {} {} {}", request.method(), request.remote_addr(), request.url());
-        return empty_with_status(401);
+fn endpoint_allowed(scopes: &[String], endpoint: &str) -> bool {
+    scopes.iter().any(|s| {
+        s == "*" || s == endpoint || (s.ends_with('*') && endpoint.starts_with(&s[..s.len()-1]))
+    })
+}
+
+fn handle_http_request(request: &Request, conf: &Config, jobs: &JobRegistry) -> Response {
+    let scopes = match check_auth(request, conf) {
+        Some(scopes) => scopes,
+        None => {
+            println!("Authorizatin Failed: {} {} {}", request.method(), request.remote_addr(), request.url());
+            return empty_with_status(401);
+        }
+    };
+
+    if let Some(job_id) = request.url().strip_prefix("/jobs/") {
+        return match request.method() {
+            "GET" => job_status(jobs, job_id, &scopes),
+            "DELETE" => job_kill(jobs, job_id, &scopes),
+            _ => empty_with_status(500),
+        };
     }
 
     for c in &conf.commands {
         if c.endpoint == request.url() {
-            return execute(c, request);
+            if !endpoint_allowed(&scopes, &c.endpoint) {
+                println!("Forbidden: {} {} {} (token scope does not cover this endpoint)", request.method(), request.url(), request.remote_addr());
+                return empty_with_status(403);
+            }
+            return execute(c, request, jobs);
         }
     }
 
@@ -317,21 +840,71 @@ fn handle_http_request(request: &Request, conf: &Config) -> Response {
     Response::empty_404()
 }
 
+#[cfg(feature = "mint-token")]
+#[derive(Subcommand, Debug)]
+enum SubCommand {
+    /// Mint an offline HMAC bearer token without starting the server.
+    MintToken {
+        /// HMAC secret, must match the `secret` configured in the server's Config.
+        #[arg(long)]
+        secret: String,
+        /// Token lifetime in seconds from now.
+        #[arg(long)]
+        expires_in: u64,
+        /// Comma-separated list of endpoint paths the token grants access to (a trailing `*` matches a prefix).
+        #[arg(long, value_delimiter = ',')]
+        scopes: Vec<String>,
+    },
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
     #[arg(short, long, default_value_t = String::from("teleapi.yml"))]
     config: String,
+
+    #[cfg(feature = "mint-token")]
+    #[command(subcommand)]
+    command: Option<SubCommand>,
 }
 
 fn main(){
     let args = Args::parse();
 
+    #[cfg(feature = "mint-token")]
+    if let Some(SubCommand::MintToken { secret, expires_in, scopes }) = args.command {
+        let token = mint_token(&secret, current_unix_time() + expires_in, scopes);
+        println!("{}", token);
+        return;
+    }
+
     let cf = std::fs::File::open(args.config).expect("Unable to open configuration file.");
     let conf: Config = serde_yaml::from_reader(cf).expect("Failed to parse configuration file.");
 
-    println!("Starting server on {}:{}", conf.listen, conf.listen_port);
-
-    rouille::start_server(format!("{}:{}", conf.listen, conf.listen_port),
-        move |request| {handle_http_request(request, &conf)});
+    let addr = format!("{}:{}", conf.listen, conf.listen_port);
+    let jobs: JobRegistry = Arc::new(Mutex::new(HashMap::new()));
+
+    match (&conf.tls_cert, &conf.tls_key) {
+        (Some(cert_path), Some(key_path)) => {
+            println!("Starting server on {} (TLS via rustls)", addr);
+            // Build the rustls ServerConfig purely to validate the cert/key files up front,
+            // so an unreadable or unparsable file fails fast here. This ServerConfig is
+            // then discarded: rouille's "ssl" feature re-parses the raw PEM bytes below and
+            // builds its own independent rustls config to actually terminate TLS.
+            let _ = load_rustls_server_config(cert_path, key_path);
+            let certificate = std::fs::read(cert_path).expect("Unable to read TLS certificate file.");
+            let private_key = std::fs::read(key_path).expect("Unable to read TLS private key file.");
+
+            let server = rouille::Server::new_ssl(addr, move |request| {handle_http_request(request, &conf, &jobs)},
+                certificate, private_key).expect("Failed to start TLS server.");
+            server.run();
+        },
+        (None, None) => {
+            println!("Starting server on {}", addr);
+            rouille::start_server(addr, move |request| {handle_http_request(request, &conf, &jobs)});
+        },
+        _ => {
+            panic!("Both tls_cert and tls_key must be set to enable TLS.");
+        }
+    }
 }